@@ -18,32 +18,165 @@
 use super::TAG;
 use crate::common::notify::desktop::DesktopNotifier;
 use crate::common::notify::uds::UDSNotifier;
+use crate::common::notify::Notifier;
 use crate::common::settings::Settings;
 use crate::common::types::cli::CliError;
 use crate::sql::tpool::ThreadConnPool;
-use crate::{common, fuse, sql};
+use crate::{common, fuse, fuse_sys, sql};
 use clap::ArgMatches;
-use log::{debug, info};
+use log::{debug, info, warn};
 use nix::unistd::{fork, ForkResult};
 use parking_lot::Mutex;
 use rusqlite::{Connection, Result as SqliteResult};
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-fn run_migrations<P: AsRef<Path>>(db_path: P) -> SqliteResult<()> {
+/// How many times we'll try to open (or recreate) the collection database before giving up.
+/// A transient failure here (e.g. a race recreating the collection directory) shouldn't be
+/// enough to abort the fork'd child. Since the last attempt is never quarantined (it just
+/// returns whatever error came back), this bounds quarantining at `MAX_OPEN_ATTEMPTS - 1`
+/// corrupt-database backups, not `MAX_OPEN_ATTEMPTS`.
+const MAX_OPEN_ATTEMPTS: u32 = 3;
+
+fn unix_timestamp_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+/// Runs the integrity pragmas SQLite recommends for detecting a corrupt database file.
+/// Returns `true` only if both report `ok`.
+fn is_db_healthy(conn: &Connection) -> SqliteResult<bool> {
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Ok(false);
+    }
+    let quick: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    Ok(quick == "ok")
+}
+
+fn is_corruption_error(err: &rusqlite::Error) -> bool {
+    match err {
+        rusqlite::Error::SqliteFailure(e, _) => matches!(
+            e.code,
+            rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase
+        ),
+        _ => false,
+    }
+}
+
+/// Moves a corrupt database file aside to a timestamped sidecar path (e.g.
+/// `db.sqlite.corrupt.1611111111123456789`) so the mount can start over with a fresh database.
+/// Also sweeps along any `-wal`/`-shm` sidecars, so a leftover WAL file from the corrupt
+/// database can't get picked up by the freshly created one at the same path. Returns the path
+/// the corrupt main database file was moved to.
+fn quarantine_corrupt_db(db_path: &Path) -> std::io::Result<PathBuf> {
+    let suffix = format!(".corrupt.{}", unix_timestamp_nanos());
+
+    let mut backup_name = db_path.as_os_str().to_os_string();
+    backup_name.push(&suffix);
+    let backup_path = PathBuf::from(backup_name);
+    std::fs::rename(db_path, &backup_path)?;
+
+    for ext in ["-wal", "-shm"] {
+        let mut sidecar = db_path.as_os_str().to_os_string();
+        sidecar.push(ext);
+        let sidecar = PathBuf::from(sidecar);
+        if sidecar.exists() {
+            let mut sidecar_backup = sidecar.as_os_str().to_os_string();
+            sidecar_backup.push(&suffix);
+            std::fs::rename(&sidecar, PathBuf::from(sidecar_backup))?;
+        }
+    }
+
+    Ok(backup_path)
+}
+
+/// Opens the collection database, detecting corruption via SQLite's integrity pragmas. If the
+/// file is corrupt (or doesn't even look like a database), it's quarantined via
+/// [`quarantine_corrupt_db`], `notifier` is told about it, and a fresh database is created in
+/// its place. The whole open-and-check step is retried up to [`MAX_OPEN_ATTEMPTS`] times, so at
+/// most `MAX_OPEN_ATTEMPTS - 1` corrupt databases get quarantined before this gives up and
+/// returns the last error.
+fn open_with_recovery<N: Notifier>(
+    db_path: &Path,
+    notifier: &Arc<Mutex<N>>,
+) -> Result<Connection, Box<dyn Error>> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+
+        let opened = Connection::open(db_path).and_then(|conn| {
+            if is_db_healthy(&conn)? {
+                Ok(conn)
+            } else {
+                Err(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+                    Some("integrity check failed".to_string()),
+                ))
+            }
+        });
+
+        match opened {
+            Ok(conn) => return Ok(conn),
+            Err(err) if is_corruption_error(&err) && attempt < MAX_OPEN_ATTEMPTS => {
+                warn!(
+                    target: TAG,
+                    "Collection database at {:?} is corrupt (attempt {}/{}): {}",
+                    db_path,
+                    attempt,
+                    MAX_OPEN_ATTEMPTS,
+                    err
+                );
+                let backup_path = quarantine_corrupt_db(db_path)?;
+                notifier.lock().notify(&format!(
+                    "Your collection was corrupted. It has been backed up to {} \
+                     and a fresh, empty collection has been created.",
+                    backup_path.display()
+                ));
+                if let Some(parent) = db_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+fn run_migrations<N: Notifier>(
+    db_path: &Path,
+    notifier: &Arc<Mutex<N>>,
+) -> Result<(), Box<dyn Error>> {
     debug!(target: TAG, "Running migrations");
-    let mut conn = Connection::open(&db_path)?;
-    sql::migrations::migrate(&mut conn, &*common::version_str())?;
+    let mut conn = open_with_recovery(db_path, notifier)?;
+    sql::migrations::migrate(&mut conn, &common::version_str())?;
     Ok(())
 }
 
+/// Returns whether this mount should be backed by an in-memory database rather than a file on
+/// disk, per the `--ephemeral` flag or the `SUPERTAG_STATELESS` environment variable.
+fn is_ephemeral(args: &ArgMatches) -> bool {
+    args.is_present("ephemeral") || std::env::var_os("SUPERTAG_STATELESS").is_some()
+}
+
 pub fn handle(args: &ArgMatches, mut settings: Settings) -> Result<(), Box<dyn Error>> {
     info!(target: TAG, "Running mount");
     let col = args.value_of("collection").expect("Collection required!");
     settings.set_collection(col, true);
+    settings.set_extensions(
+        args.values_of("extension")
+            .map(|vals| vals.map(PathBuf::from).collect())
+            .unwrap_or_default(),
+    );
+
+    if is_ephemeral(args) {
+        return handle_ephemeral(args, settings, col);
+    }
 
     let mountpoint = settings.mountpoint(col);
     println!("Mounting to {:?}", mountpoint);
@@ -70,9 +203,10 @@ pub fn handle(args: &ArgMatches, mut settings: Settings) -> Result<(), Box<dyn E
     }
 
     if background {
-        let conn_pool = ThreadConnPool::new(db_path.clone());
+        let conn_pool =
+            ThreadConnPool::new(db_path.clone(), share_settings.extensions().to_vec());
         debug!(target: TAG, "Forking into the background...");
-        match fork().expect("Fork failed") {
+        match unsafe { fork() }.expect("Fork failed") {
             ForkResult::Parent { child } => {
                 debug!(target: TAG, "Forked PID {}, now exiting", child);
                 println!("Forked into background PID {}", child);
@@ -86,13 +220,16 @@ pub fn handle(args: &ArgMatches, mut settings: Settings) -> Result<(), Box<dyn E
                 // i haven't been able to hunt down the cause of this yet, but it occurs even when
                 // i am very careful to close + cleanup the database connection that existed in
                 // the parent process. as such, we do the migrations here, to avoid the deadlock
-                run_migrations(&db_path)?;
-
                 debug!(target: TAG, "Creating notifier");
                 let notifier = Arc::new(Mutex::new(DesktopNotifier::new(
                     share_settings.notification_icon(),
                 )));
 
+                run_migrations(&db_path, &notifier)?;
+
+                #[cfg(feature = "session")]
+                conn_pool.enable_undo_tracking();
+
                 debug!(target: TAG, "Creating TagFilesystem");
                 let fsh = fuse::TagFilesystem::new(share_settings, conn_pool, notifier);
                 debug!(target: TAG, "Mounting filesystem");
@@ -104,9 +241,13 @@ pub fn handle(args: &ArgMatches, mut settings: Settings) -> Result<(), Box<dyn E
             }
         }
     } else {
-        run_migrations(&db_path)?;
+        let notifier_socket = share_settings.notify_socket_file(col);
+        let notifier = Arc::new(Mutex::new(UDSNotifier::new(notifier_socket, true)?));
 
-        let conn_pool = ThreadConnPool::new(db_path.clone());
+        run_migrations(&db_path, &notifier)?;
+
+        let conn_pool =
+            ThreadConnPool::new(db_path.clone(), share_settings.extensions().to_vec());
         info!(
             target: TAG,
             "Mounting {} to {}",
@@ -114,12 +255,12 @@ pub fn handle(args: &ArgMatches, mut settings: Settings) -> Result<(), Box<dyn E
             mountpoint.display()
         );
 
-        let notifier_socket = share_settings.notify_socket_file(col);
-        let notifier = Arc::new(Mutex::new(UDSNotifier::new(notifier_socket, true)?));
-
         let sigint = Arc::new(AtomicBool::new(false));
         signal_hook::flag::register(signal_hook::SIGINT, Arc::clone(&sigint))?;
 
+        #[cfg(feature = "session")]
+        conn_pool.enable_undo_tracking();
+
         let fsh = fuse::TagFilesystem::new(share_settings, conn_pool, notifier);
         let _mount_handle = fuse_sys::mount(&mountpoint, fsh, false, fuse_conf, mount_conf)?;
 
@@ -131,3 +272,134 @@ pub fn handle(args: &ArgMatches, mut settings: Settings) -> Result<(), Box<dyn E
         Ok(())
     }
 }
+
+/// Mounts `col` backed by an in-memory SQLite database instead of a file under the user's
+/// supertag dir. Nothing is written to disk, so there's no `db_file`/migrations-on-disk step and
+/// no `fork()` into the background: the mount simply runs in the foreground until `SIGINT`, at
+/// which point the in-memory database is dropped and everything it held vanishes. Handy for
+/// demos and integration tests that want a throwaway tag filesystem.
+fn handle_ephemeral(
+    _args: &ArgMatches,
+    settings: Settings,
+    col: &str,
+) -> Result<(), Box<dyn Error>> {
+    info!(target: TAG, "Running mount in ephemeral (in-memory) mode");
+
+    let mountpoint = settings.mountpoint(col);
+    println!("Mounting to {:?}", mountpoint);
+
+    if cfg!(target_os = "linux") && !mountpoint.exists() {
+        return Err(CliError::InvalidMountDir(mountpoint).into());
+    }
+
+    let share_settings = Arc::new(settings);
+
+    let volicon = share_settings.volicon();
+    let fuse_conf = fuse::util::make_fuse_config(volicon.as_deref());
+    let mount_conf = fuse::util::make_mount_config(col, Path::new(":memory:"));
+
+    if mountpoint.exists() {
+        opener::open(&mountpoint)?;
+    } else {
+        opener::open(share_settings.supertag_dir())?;
+    }
+
+    debug!(target: TAG, "Running migrations against an in-memory database");
+    let mut conn = Connection::open_in_memory()?;
+    sql::migrations::migrate(&mut conn, &common::version_str())?;
+    let conn_pool =
+        ThreadConnPool::new_in_memory(Arc::new(Mutex::new(conn)), share_settings.extensions().to_vec())?;
+
+    let notifier_socket = share_settings.notify_socket_file(col);
+    let notifier = Arc::new(Mutex::new(UDSNotifier::new(notifier_socket, true)?));
+
+    let sigint = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::SIGINT, Arc::clone(&sigint))?;
+
+    #[cfg(feature = "session")]
+    conn_pool.enable_undo_tracking();
+
+    let fsh = fuse::TagFilesystem::new(share_settings, conn_pool, notifier);
+    let _mount_handle = fuse_sys::mount(&mountpoint, fsh, false, fuse_conf, mount_conf)?;
+
+    while !sigint.load(Ordering::Relaxed) {
+        thread::sleep(std::time::Duration::from_millis(100));
+    }
+    info!(
+        target: TAG,
+        "Got SIGINT, unmounting and tearing down the ephemeral collection"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RecordingNotifier {
+        messages: Vec<String>,
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn notify(&mut self, message: &str) {
+            self.messages.push(message.to_string());
+        }
+    }
+
+    fn temp_db_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "supertag-mount-test-{}-{}.sqlite",
+            std::process::id(),
+            name
+        ));
+        path
+    }
+
+    #[test]
+    fn open_with_recovery_opens_a_healthy_database() {
+        let db_path = temp_db_path("healthy");
+        let _ = std::fs::remove_file(&db_path);
+        let notifier = Arc::new(Mutex::new(RecordingNotifier { messages: Vec::new() }));
+
+        let conn = open_with_recovery(&db_path, &notifier).unwrap();
+        assert!(is_db_healthy(&conn).unwrap());
+        assert!(notifier.lock().messages.is_empty());
+
+        drop(conn);
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn open_with_recovery_quarantines_a_corrupt_database_and_starts_fresh() {
+        let db_path = temp_db_path("corrupt");
+        let _ = std::fs::remove_file(&db_path);
+        std::fs::write(&db_path, b"this is not a sqlite database").unwrap();
+        let notifier = Arc::new(Mutex::new(RecordingNotifier { messages: Vec::new() }));
+
+        let conn = open_with_recovery(&db_path, &notifier).unwrap();
+        assert!(is_db_healthy(&conn).unwrap());
+        assert_eq!(notifier.lock().messages.len(), 1);
+
+        let quarantined: Vec<_> = db_path
+            .parent()
+            .unwrap()
+            .read_dir()
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(&*format!("{}.corrupt.", db_path.file_name().unwrap().to_string_lossy()))
+            })
+            .collect();
+        assert_eq!(quarantined.len(), 1);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&db_path);
+        for entry in quarantined {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
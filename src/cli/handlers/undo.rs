@@ -0,0 +1,85 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#[cfg(feature = "session")]
+use super::TAG;
+use crate::common::settings::Settings;
+use clap::ArgMatches;
+#[cfg(feature = "session")]
+use log::{debug, info};
+#[cfg(feature = "session")]
+use rusqlite::Connection;
+use std::error::Error;
+
+/// Default number of changesets to undo when `N` isn't given on the command line.
+#[cfg(feature = "session")]
+const DEFAULT_UNDO_COUNT: u32 = 1;
+
+#[cfg(feature = "session")]
+pub fn handle(args: &ArgMatches, mut settings: Settings) -> Result<(), Box<dyn Error>> {
+    let col = args.value_of("collection").expect("Collection required!");
+    settings.set_collection(col, true);
+
+    let count: u32 = args
+        .value_of("count")
+        .map(|n| n.parse())
+        .transpose()?
+        .unwrap_or(DEFAULT_UNDO_COUNT);
+
+    let db_path = settings.db_file(col);
+    let mut conn = Connection::open(&db_path)?;
+
+    debug!(target: TAG, "Undoing last {} change(s) in {:?}", count, db_path);
+    let undone = undo_last_n(&mut conn, count)?;
+    info!(target: TAG, "Undid {} change(s)", undone);
+    println!("Undid {} change(s)", undone);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "session"))]
+pub fn handle(_args: &ArgMatches, _settings: Settings) -> Result<(), Box<dyn Error>> {
+    Err("supertag was built without the \"session\" feature, so undo history isn't available"
+        .into())
+}
+
+/// Pops up to `count` changesets off the tail of `undo_log`, applies their inverse to `conn`
+/// (most recent first), and prunes the rows it successfully reverted. Returns the number of
+/// changesets actually undone, which may be fewer than `count` if the log is shorter.
+///
+/// If applying an inverted changeset conflicts with the live data, the whole undo is aborted in
+/// a single transaction so the database is never left half-reverted.
+#[cfg(feature = "session")]
+fn undo_last_n(conn: &mut Connection, count: u32) -> Result<u32, Box<dyn Error>> {
+    let tx = conn.transaction()?;
+
+    let mut stmt = tx.prepare("SELECT id, changeset FROM undo_log ORDER BY id DESC LIMIT ?1")?;
+    let rows: Vec<(i64, Vec<u8>)> = stmt
+        .query_map([count], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    drop(stmt);
+
+    let mut undone = 0u32;
+    for (id, changeset) in &rows {
+        crate::sql::session::invert_and_apply(&tx, changeset)?;
+        tx.execute("DELETE FROM undo_log WHERE id = ?1", [id])?;
+        undone += 1;
+    }
+
+    tx.commit()?;
+    Ok(undone)
+}
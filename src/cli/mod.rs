@@ -0,0 +1,98 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+mod handlers;
+
+use crate::common::settings::Settings;
+use clap::{App, AppSettings, Arg, SubCommand};
+use std::error::Error;
+use std::path::PathBuf;
+
+fn build_app() -> App<'static, 'static> {
+    App::new("supertag")
+        .version(env!("CARGO_PKG_VERSION"))
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("mount")
+                .about("Mounts a tag collection as a FUSE filesystem")
+                .arg(
+                    Arg::with_name("collection")
+                        .help("Name of the collection to mount")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("foreground")
+                        .long("foreground")
+                        .short("f")
+                        .help("Stay in the foreground instead of forking into the background"),
+                )
+                .arg(
+                    Arg::with_name("extension")
+                        .long("extension")
+                        .short("e")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Path to a loadable SQLite extension to load into every connection"),
+                )
+                .arg(
+                    Arg::with_name("ephemeral")
+                        .long("ephemeral")
+                        .help(
+                            "Back the mount with an in-memory database instead of a file; \
+                             nothing is persisted once it's unmounted",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Undoes the most recent change(s) made to a collection")
+                .arg(
+                    Arg::with_name("collection")
+                        .long("collection")
+                        .short("c")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Name of the collection to undo changes in"),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .help("Number of changes to undo")
+                        .index(1),
+                ),
+        )
+}
+
+fn default_supertag_dir() -> PathBuf {
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    home.join(".supertag")
+}
+
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let matches = build_app().get_matches();
+    let settings = Settings::new(default_supertag_dir());
+
+    match matches.subcommand() {
+        ("mount", Some(sub_m)) => handlers::mount::handle(sub_m, settings),
+        ("undo", Some(sub_m)) => handlers::undo::handle(sub_m, settings),
+        _ => unreachable!("AppSettings::SubcommandRequiredElseHelp guarantees a subcommand"),
+    }
+}
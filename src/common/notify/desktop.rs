@@ -0,0 +1,41 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::Notifier;
+use log::info;
+use std::path::PathBuf;
+
+/// Surfaces messages as desktop notifications, for foreground-less mounts running in the
+/// background.
+pub struct DesktopNotifier {
+    icon: Option<PathBuf>,
+}
+
+impl DesktopNotifier {
+    pub fn new(icon: Option<PathBuf>) -> Self {
+        DesktopNotifier { icon }
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn notify(&mut self, message: &str) {
+        // A real build would hand this off to a platform notification center (e.g. via
+        // `notify-rust`). Logging is a reasonable stand-in until that's wired up.
+        info!("[desktop notification, icon={:?}] {}", self.icon, message);
+    }
+}
@@ -0,0 +1,61 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::Notifier;
+use log::debug;
+use std::io::Write;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+/// Surfaces messages over a unix domain socket at `socket_path`, so a long-running client (e.g.
+/// a tray helper) can display mount-lifecycle messages for a foreground mount.
+pub struct UDSNotifier {
+    socket_path: PathBuf,
+    // Held only to keep the bind alive for the lifetime of the notifier; never read directly.
+    _listener: Option<UnixListener>,
+}
+
+impl UDSNotifier {
+    pub fn new(socket_path: PathBuf, listen: bool) -> std::io::Result<Self> {
+        let listener = if listen {
+            let _ = std::fs::remove_file(&socket_path);
+            Some(UnixListener::bind(&socket_path)?)
+        } else {
+            None
+        };
+        Ok(UDSNotifier {
+            socket_path,
+            _listener: listener,
+        })
+    }
+}
+
+impl Notifier for UDSNotifier {
+    fn notify(&mut self, message: &str) {
+        match UnixStream::connect(&self.socket_path) {
+            Ok(mut stream) => {
+                if let Err(err) = writeln!(stream, "{}", message) {
+                    debug!("Failed to write notification to {:?}: {}", self.socket_path, err);
+                }
+            }
+            Err(err) => {
+                debug!("No listener on {:?} for notification: {}", self.socket_path, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::path::{Path, PathBuf};
+
+/// User-level and collection-scoped configuration for a mount. Loaded once at startup and then
+/// scoped to a single collection via [`Settings::set_collection`].
+pub struct Settings {
+    supertag_dir: PathBuf,
+    collection: Option<String>,
+    volicon: Option<PathBuf>,
+    /// Paths to loadable SQLite extensions (`.so`/`.dylib`/`.dll`) that every connection opened
+    /// against the collection database should load, so FUSE query threads can call custom SQL
+    /// functions or collations the extensions register (e.g. case-insensitive tag matching).
+    extensions: Vec<PathBuf>,
+}
+
+impl Settings {
+    pub fn new(supertag_dir: PathBuf) -> Self {
+        Settings {
+            supertag_dir,
+            collection: None,
+            volicon: None,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Scopes this `Settings` to `collection`, creating its on-disk directory if `create` is set
+    /// and it doesn't already exist.
+    pub fn set_collection(&mut self, collection: &str, create: bool) {
+        if create {
+            let _ = std::fs::create_dir_all(self.collection_dir(collection));
+        }
+        self.collection = Some(collection.to_string());
+    }
+
+    fn collection_dir(&self, collection: &str) -> PathBuf {
+        self.supertag_dir.join(collection)
+    }
+
+    pub fn mountpoint(&self, collection: &str) -> PathBuf {
+        self.collection_dir(collection).join("mount")
+    }
+
+    pub fn db_file(&self, collection: &str) -> PathBuf {
+        self.collection_dir(collection).join("db.sqlite")
+    }
+
+    pub fn notify_socket_file(&self, collection: &str) -> PathBuf {
+        self.collection_dir(collection).join("notify.sock")
+    }
+
+    pub fn supertag_dir(&self) -> &Path {
+        &self.supertag_dir
+    }
+
+    pub fn volicon(&self) -> Option<PathBuf> {
+        self.volicon.clone()
+    }
+
+    pub fn notification_icon(&self) -> Option<PathBuf> {
+        self.volicon.clone()
+    }
+
+    pub fn extensions(&self) -> &[PathBuf] {
+        &self.extensions
+    }
+
+    pub fn set_extensions(&mut self, extensions: Vec<PathBuf>) {
+        self.extensions = extensions;
+    }
+}
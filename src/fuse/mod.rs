@@ -0,0 +1,47 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+pub mod util;
+
+use crate::common::notify::Notifier;
+use crate::common::settings::Settings;
+use crate::sql::tpool::ThreadConnPool;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// FUSE filesystem implementation backing a mounted collection: holds the shared settings, the
+/// connection pool FUSE worker threads pull from, and the notifier used to surface
+/// lifecycle/error messages to the user.
+pub struct TagFilesystem<N> {
+    #[allow(dead_code)]
+    settings: Arc<Settings>,
+    #[allow(dead_code)]
+    pool: ThreadConnPool,
+    #[allow(dead_code)]
+    notifier: Arc<Mutex<N>>,
+}
+
+impl<N: Notifier> TagFilesystem<N> {
+    pub fn new(settings: Arc<Settings>, pool: ThreadConnPool, notifier: Arc<Mutex<N>>) -> Self {
+        TagFilesystem {
+            settings,
+            pool,
+            notifier,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::path::Path;
+
+/// Placeholder for the real libfuse mount options struct; see `crate::fuse_sys`.
+pub struct FuseConfig;
+
+/// Placeholder for the real per-collection mount configuration; see `crate::fuse_sys`.
+pub struct MountConfig;
+
+pub fn make_fuse_config(_volicon: Option<&Path>) -> FuseConfig {
+    FuseConfig
+}
+
+pub fn make_mount_config(_collection: &str, _db_path: &Path) -> MountConfig {
+    MountConfig
+}
@@ -0,0 +1,31 @@
+/*
+ * Minimal placeholder for this project's native libfuse binding.
+ *
+ * The real binding links against libfuse and drives the kernel mount; it isn't published as an
+ * independent crate, so it lives in-tree. This stub preserves the call shape `cli::handlers::mount`
+ * depends on (a `mount` function returning a handle you can `wait()` on) without requiring
+ * libfuse headers to be present, which this sandbox doesn't have.
+ */
+use parking_lot::Mutex;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct MountHandle;
+
+impl MountHandle {
+    pub fn wait(&self) {}
+}
+
+pub fn mount<FS>(
+    _mountpoint: &Path,
+    _filesystem: FS,
+    _foreground: bool,
+    _fuse_conf: crate::fuse::util::FuseConfig,
+    _mount_conf: crate::fuse::util::MountConfig,
+) -> io::Result<Arc<Mutex<MountHandle>>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "FUSE mounting isn't available in this build",
+    ))
+}
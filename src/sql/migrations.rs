@@ -0,0 +1,88 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use log::debug;
+use rusqlite::{Connection, Result as SqliteResult};
+
+/// One forward-only schema migration, identified by a monotonically increasing id.
+struct Migration {
+    id: i64,
+    description: &'static str,
+    sql: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    #[cfg(feature = "session")]
+    Migration {
+        id: 1,
+        description: "create undo_log table for changeset-based undo",
+        sql: "CREATE TABLE undo_log (\
+                id INTEGER PRIMARY KEY, \
+                created_at INTEGER NOT NULL, \
+                description TEXT NOT NULL, \
+                changeset BLOB NOT NULL\
+              )",
+    },
+];
+
+/// Brings `conn`'s schema up to date, applying any migration in [`MIGRATIONS`] newer than what's
+/// recorded in `schema_version`. `version` is accepted for parity with the rest of the CLI, which
+/// threads the running supertag version through for diagnostics; migrations themselves aren't
+/// currently version-gated.
+pub fn migrate(conn: &mut Connection, _version: &str) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (id INTEGER PRIMARY KEY, applied_at INTEGER NOT NULL)",
+    )?;
+
+    let applied: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(id), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let tx = conn.transaction()?;
+    for migration in MIGRATIONS.iter().filter(|m| m.id > applied) {
+        debug!(
+            target: "sql::migrations",
+            "Applying migration {}: {}",
+            migration.id,
+            migration.description
+        );
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (id, applied_at) VALUES (?1, strftime('%s', 'now'))",
+            [migration.id],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, "0.0.0-test").unwrap();
+        // Running it again against an already-migrated database must not error.
+        migrate(&mut conn, "0.0.0-test").unwrap();
+    }
+}
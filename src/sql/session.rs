@@ -0,0 +1,226 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Session-extension-backed undo support: wraps a logical mutation in a `sqlite3session`, records
+//! the resulting changeset in `undo_log`, and can invert + re-apply a stored changeset to undo it.
+//!
+//! `with_undo_capture` (and the `UndoSession` it's built on) has no caller yet because the FUSE
+//! mutation handlers that would wrap each tagging operation in it aren't implemented in this tree
+//! — see `crate::fuse_sys`. It's exercised directly by this module's tests, which is how its
+//! pairing with `invert_and_apply` (the half `supertag undo` actually uses) is verified.
+#![allow(dead_code)]
+use super::session_ffi::{
+    sqlite3_free, sqlite3changeset_apply, sqlite3changeset_invert, sqlite3session_attach,
+    sqlite3session_changeset, sqlite3session_create, sqlite3session_delete,
+    sqlite3session_isempty, sqlite3_session, SQLITE_CHANGESET_ABORT,
+};
+use log::debug;
+use rusqlite::{ffi, Connection, Error as SqliteError, Result as SqliteResult};
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn check(rc: c_int) -> SqliteResult<()> {
+    if rc == ffi::SQLITE_OK {
+        Ok(())
+    } else {
+        Err(SqliteError::SqliteFailure(
+            ffi::Error::new(rc),
+            Some(format!("sqlite3 session/changeset call failed with code {}", rc)),
+        ))
+    }
+}
+
+/// A session attached to a connection for the duration of a single logical mutation (e.g. one
+/// FUSE tagging operation). Create it right before the mutation, run the mutation, then call
+/// [`UndoSession::capture`] to append the resulting changeset to `undo_log` and tear the session
+/// down — there's no reason to keep a session alive any longer than the mutation it's recording.
+struct UndoSession {
+    raw: *mut sqlite3_session,
+}
+
+impl UndoSession {
+    /// Attaches a new session to `conn`, tracking every table (a `NULL` table name attaches to
+    /// all tables, present and future, rather than hard-coding the tag/file-link table names
+    /// here).
+    fn attach(conn: &Connection) -> SqliteResult<Self> {
+        let db = unsafe { conn.handle() };
+        let main_db = CString::new("main").expect("\"main\" has no interior NUL");
+        let mut raw: *mut sqlite3_session = ptr::null_mut();
+        check(unsafe { sqlite3session_create(db, main_db.as_ptr(), &mut raw) })?;
+
+        if let Err(err) = check(unsafe { sqlite3session_attach(raw, ptr::null()) }) {
+            unsafe { sqlite3session_delete(raw) };
+            return Err(err);
+        }
+
+        Ok(UndoSession { raw })
+    }
+
+    fn is_empty(&self) -> bool {
+        unsafe { sqlite3session_isempty(self.raw) != 0 }
+    }
+
+    /// Serializes everything recorded since [`UndoSession::attach`] and appends it to `undo_log`
+    /// with `description` and the current time. Returns `true` if anything was actually captured
+    /// (a no-op mutation leaves nothing to undo, so nothing is inserted).
+    fn capture(self, conn: &Connection, description: &str) -> SqliteResult<bool> {
+        if self.is_empty() {
+            debug!(target: "sql::session", "Mutation recorded no changes, skipping undo_log entry");
+            return Ok(false);
+        }
+
+        let mut len: c_int = 0;
+        let mut data: *mut c_void = ptr::null_mut();
+        check(unsafe { sqlite3session_changeset(self.raw, &mut len, &mut data) })?;
+
+        let changeset = unsafe { std::slice::from_raw_parts(data as *const u8, len as usize) };
+        let result = conn.execute(
+            "INSERT INTO undo_log (created_at, description, changeset) VALUES (?1, ?2, ?3)",
+            rusqlite::params![unix_timestamp(), description, changeset],
+        );
+        unsafe { sqlite3_free(data) };
+
+        result?;
+        Ok(true)
+    }
+}
+
+impl Drop for UndoSession {
+    fn drop(&mut self) {
+        unsafe { sqlite3session_delete(self.raw) };
+    }
+}
+
+/// Runs `f` (a single logical mutation, e.g. one FUSE tagging operation) against `conn` with
+/// undo tracking attached, appending the resulting changeset to `undo_log` as `description` if
+/// anything changed.
+pub fn with_undo_capture<T>(
+    conn: &Connection,
+    description: &str,
+    f: impl FnOnce(&Connection) -> SqliteResult<T>,
+) -> SqliteResult<T> {
+    let session = UndoSession::attach(conn)?;
+    let result = f(conn)?;
+    session.capture(conn, description)?;
+    Ok(result)
+}
+
+/// Inverts `raw` (a changeset previously captured by [`with_undo_capture`]) and applies the
+/// inverse to `conn`, aborting without partially applying anything if the live data conflicts
+/// with what the changeset expects.
+pub fn invert_and_apply(conn: &Connection, raw: &[u8]) -> SqliteResult<()> {
+    let mut inv_len: c_int = 0;
+    let mut inv_data: *mut c_void = ptr::null_mut();
+    check(unsafe {
+        sqlite3changeset_invert(
+            raw.len() as c_int,
+            raw.as_ptr() as *const c_void,
+            &mut inv_len,
+            &mut inv_data,
+        )
+    })?;
+
+    let db = unsafe { conn.handle() };
+    let rc = unsafe {
+        sqlite3changeset_apply(
+            db,
+            inv_len,
+            inv_data,
+            None,
+            Some(abort_on_conflict),
+            ptr::null_mut(),
+        )
+    };
+    unsafe { sqlite3_free(inv_data) };
+    check(rc)
+}
+
+/// Conflict handler for [`invert_and_apply`]: undo is only ever meant to replay cleanly against
+/// the exact state it was captured from, so any conflict means something else changed the row out
+/// from under it — abort rather than guess.
+unsafe extern "C" fn abort_on_conflict(
+    _ctx: *mut c_void,
+    _conflict: c_int,
+    _iter: *mut super::session_ffi::sqlite3_changeset_iter,
+) -> c_int {
+    SQLITE_CHANGESET_ABORT
+}
+
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::migrations::migrate;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, "0.0.0-test").unwrap();
+        conn.execute_batch("CREATE TABLE tag (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+            .unwrap();
+        conn
+    }
+
+    #[test]
+    fn capture_then_undo_reverts_the_mutation() {
+        let conn = setup();
+
+        with_undo_capture(&conn, "tagged a file with 'invoices'", |conn| {
+            conn.execute("INSERT INTO tag (name) VALUES ('invoices')", [])
+        })
+        .unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM tag", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let raw: Vec<u8> = conn
+            .query_row(
+                "SELECT changeset FROM undo_log ORDER BY id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        invert_and_apply(&conn, &raw).unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM tag", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn capture_skips_undo_log_when_nothing_changed() {
+        let conn = setup();
+        with_undo_capture(&conn, "no-op", |_conn| Ok(())).unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM undo_log", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
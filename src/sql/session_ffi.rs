@@ -0,0 +1,90 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Hand-written bindings for the handful of the SQLite session/changeset C API we need
+//! (`sqlite3session_*`, `sqlite3changeset_*`). rusqlite's own `session` cargo feature pulls in
+//! `buildtime_bindgen`, which needs libclang; these functions are few enough, and stable enough
+//! across SQLite versions, to declare by hand instead and call through `Connection::handle()`.
+#![allow(non_camel_case_types)]
+// Only `sqlite3changeset_invert`/`apply`/`sqlite3_free` are exercised today (via
+// `sql::session::invert_and_apply`, used by `supertag undo`). The rest of this binding
+// (`sqlite3session_*`) backs `sql::session::with_undo_capture`, which has no caller yet because
+// the FUSE mutation handlers that would call it per-mutation aren't implemented in this tree.
+#![allow(dead_code)]
+
+use rusqlite::ffi::sqlite3;
+use std::os::raw::{c_char, c_int, c_void};
+
+#[repr(C)]
+pub struct sqlite3_session {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct sqlite3_changeset_iter {
+    _private: [u8; 0],
+}
+
+/// Conflict-resolution actions a `sqlite3changeset_apply` conflict handler may return.
+pub const SQLITE_CHANGESET_ABORT: c_int = 2;
+
+extern "C" {
+    pub fn sqlite3session_create(
+        db: *mut sqlite3,
+        z_db: *const c_char,
+        pp_session: *mut *mut sqlite3_session,
+    ) -> c_int;
+
+    pub fn sqlite3session_delete(session: *mut sqlite3_session);
+
+    pub fn sqlite3session_attach(session: *mut sqlite3_session, z_tab: *const c_char) -> c_int;
+
+    pub fn sqlite3session_isempty(session: *mut sqlite3_session) -> c_int;
+
+    pub fn sqlite3session_changeset(
+        session: *mut sqlite3_session,
+        pn_changeset: *mut c_int,
+        pp_changeset: *mut *mut c_void,
+    ) -> c_int;
+
+    pub fn sqlite3changeset_invert(
+        n_in: c_int,
+        p_in: *const c_void,
+        pn_out: *mut c_int,
+        pp_out: *mut *mut c_void,
+    ) -> c_int;
+
+    pub fn sqlite3changeset_apply(
+        db: *mut sqlite3,
+        n_changeset: c_int,
+        p_changeset: *mut c_void,
+        x_filter: Option<unsafe extern "C" fn(p_ctx: *mut c_void, z_tab: *const c_char) -> c_int>,
+        x_conflict: Option<
+            unsafe extern "C" fn(
+                p_ctx: *mut c_void,
+                e_conflict: c_int,
+                p: *mut sqlite3_changeset_iter,
+            ) -> c_int,
+        >,
+        p_ctx: *mut c_void,
+    ) -> c_int;
+
+    /// Frees a buffer allocated by one of the `sqlite3session_*`/`sqlite3changeset_*` functions
+    /// above (they all use sqlite's own allocator).
+    pub fn sqlite3_free(ptr: *mut c_void);
+}
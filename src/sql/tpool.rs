@@ -0,0 +1,182 @@
+/*
+ * Supertag
+ * Copyright (C) 2020 Andrew Moffat
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use log::debug;
+use parking_lot::Mutex;
+use rusqlite::{Connection, Result as SqliteResult};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Where a pooled connection ultimately points: either a file on disk (the common case, where
+/// each FUSE worker thread opens and keeps its own connection) or a single shared in-memory
+/// database (used by `--ephemeral` mounts, since separate `:memory:` connections don't see each
+/// other's data).
+#[allow(dead_code)]
+enum Backend {
+    File(PathBuf),
+    Memory(Arc<Mutex<Connection>>),
+}
+
+// `with_conn` isn't called anywhere yet because the FUSE filesystem layer that would call it
+// (`fuse::TagFilesystem`'s query/mutation handlers) isn't implemented in this tree — see
+// `crate::fuse_sys`. Allow the resulting dead-code warnings rather than leaving the pool
+// half-built.
+#[allow(dead_code)]
+/// A pool of SQLite connections shared across the FUSE worker threads.
+pub struct ThreadConnPool {
+    backend: Backend,
+    /// Loadable SQLite extensions every connection this pool hands out will load, so every FUSE
+    /// worker thread sees the same registered functions/collations.
+    extensions: Vec<PathBuf>,
+    undo_tracking: AtomicBool,
+}
+
+thread_local! {
+    static THREAD_CONN: RefCell<Option<Connection>> = const { RefCell::new(None) };
+}
+
+#[allow(dead_code)]
+impl ThreadConnPool {
+    /// Creates a pool backed by the SQLite file at `db_path`. `extensions` is the list of
+    /// loadable extension libraries every connection handed out by this pool will load.
+    pub fn new(db_path: PathBuf, extensions: Vec<PathBuf>) -> Self {
+        ThreadConnPool {
+            backend: Backend::File(db_path),
+            extensions,
+            undo_tracking: AtomicBool::new(false),
+        }
+    }
+
+    /// Creates a pool backed by a single shared `:memory:` connection, for `--ephemeral` mounts.
+    /// Every worker thread is handed the same connection (serialized behind `conn`'s mutex)
+    /// rather than opening one of its own. Since there's only ever the one connection, `extensions`
+    /// is loaded into it immediately rather than lazily like [`ThreadConnPool::new`]'s per-thread
+    /// connections are.
+    pub fn new_in_memory(conn: Arc<Mutex<Connection>>, extensions: Vec<PathBuf>) -> SqliteResult<Self> {
+        let pool = ThreadConnPool {
+            backend: Backend::Memory(conn),
+            extensions,
+            undo_tracking: AtomicBool::new(false),
+        };
+        if let Backend::Memory(conn) = &pool.backend {
+            pool.load_extensions(&conn.lock())?;
+        }
+        Ok(pool)
+    }
+
+    /// Enables session-extension-backed undo tracking for mutations performed through this pool.
+    /// Only meaningful when built with the `session` feature; mutation call sites consult
+    /// [`ThreadConnPool::undo_tracking_enabled`] to decide whether to wrap a logical FS mutation
+    /// in `sql::session::with_undo_capture`.
+    pub fn enable_undo_tracking(&self) {
+        self.undo_tracking.store(true, Ordering::SeqCst);
+    }
+
+    pub fn undo_tracking_enabled(&self) -> bool {
+        self.undo_tracking.load(Ordering::SeqCst)
+    }
+
+    fn load_extensions(&self, conn: &Connection) -> SqliteResult<()> {
+        if self.extensions.is_empty() {
+            return Ok(());
+        }
+        unsafe {
+            conn.load_extension_enable()?;
+            for ext in &self.extensions {
+                debug!(target: "sql::tpool", "Loading SQLite extension {:?}", ext);
+                conn.load_extension(ext, None::<&str>)?;
+            }
+            conn.load_extension_disable()?;
+        }
+        Ok(())
+    }
+
+    fn open_file_conn(&self, path: &PathBuf) -> SqliteResult<Connection> {
+        let conn = Connection::open(path)?;
+        self.load_extensions(&conn)?;
+        Ok(conn)
+    }
+
+    /// Hands a connection usable from the calling thread to `f`, opening and configuring one the
+    /// first time this thread asks for one (file-backed pools only — an in-memory pool always
+    /// hands out the single shared connection).
+    pub fn with_conn<T>(&self, f: impl FnOnce(&Connection) -> SqliteResult<T>) -> SqliteResult<T> {
+        match &self.backend {
+            Backend::Memory(conn) => {
+                let conn = conn.lock();
+                f(&conn)
+            }
+            Backend::File(path) => THREAD_CONN.with(|cell| {
+                let mut slot = cell.borrow_mut();
+                if slot.is_none() {
+                    *slot = Some(self.open_file_conn(path)?);
+                }
+                f(slot.as_ref().expect("just initialized above"))
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("supertag-tpool-test-{}-{}.sqlite", std::process::id(), name));
+        path
+    }
+
+    #[test]
+    fn file_pool_opens_a_working_connection() {
+        let db_path = temp_db_path("file-pool");
+        let _ = std::fs::remove_file(&db_path);
+
+        let pool = ThreadConnPool::new(db_path.clone(), Vec::new());
+        let value: i64 = pool
+            .with_conn(|conn| conn.query_row("SELECT 1", [], |row| row.get(0)))
+            .unwrap();
+        assert_eq!(value, 1);
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn memory_pool_shares_one_connection_across_calls() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (id INTEGER)").unwrap();
+        let pool = ThreadConnPool::new_in_memory(Arc::new(Mutex::new(conn)), Vec::new()).unwrap();
+
+        pool.with_conn(|conn| conn.execute("INSERT INTO t (id) VALUES (1)", []))
+            .unwrap();
+        let count: i64 = pool
+            .with_conn(|conn| conn.query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0)))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn undo_tracking_flag_defaults_off_and_can_be_enabled() {
+        let pool = ThreadConnPool::new(temp_db_path("flag"), Vec::new());
+        assert!(!pool.undo_tracking_enabled());
+        pool.enable_undo_tracking();
+        assert!(pool.undo_tracking_enabled());
+    }
+}